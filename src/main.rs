@@ -1,16 +1,22 @@
 #![warn(clippy::pedantic, clippy::nursery)]
 
-use scratch_server::{extract_url, parse_req, shorten_url};
-use std::io::prelude::*;
+use scratch_server::{ReqParseError, handle_err, handle_get, handle_post, keep_alive, parse_req};
+use std::collections::HashMap;
+use std::io::BufReader;
 use std::net::{TcpListener, TcpStream};
+use std::time::Duration;
+
+const IDLE_TIMEOUT: Duration = Duration::from_secs(30);
 
 fn main() -> std::io::Result<()> {
     let listener = TcpListener::bind("127.0.0.1:8887")?;
     println!("Listening on http://127.0.0.1:8887");
 
+    let mut store: HashMap<String, String> = HashMap::new();
+
     for stream in listener.incoming() {
         match stream {
-            Ok(stream) => handle_client(stream),
+            Ok(stream) => handle_client(stream, &mut store),
             Err(e) => eprintln!("Connection failed: {e}"),
         }
     }
@@ -18,47 +24,44 @@ fn main() -> std::io::Result<()> {
     Ok(())
 }
 
-fn handle_client(mut stream: TcpStream) {
-    let request = parse_req(&mut stream);
+fn handle_client(mut stream: TcpStream, store: &mut HashMap<String, String>) {
+    if let Err(e) = stream.set_read_timeout(Some(IDLE_TIMEOUT)) {
+        eprintln!("Failed to set read timeout: {e}");
+        return;
+    }
+
+    let mut reader = BufReader::new(&mut stream);
+    let mut first_request = true;
+
+    loop {
+        match parse_req(&mut reader) {
+            Ok(req) => {
+                println!("Received {} request for path {}", req.method, req.path);
 
-    match request {
-        Ok(req) => {
-            println!("Received {} request for path {}", req.method, req.path);
-            let body = if req.method == "POST" {
-                if let Some(url) = extract_url(&req.body) {
-                    shorten_url(url)
-                } else {
-                    req.body
+                let alive = keep_alive(&req);
+                match req.method.as_str() {
+                    "GET" => handle_get(reader.get_mut(), store, &req, alive),
+                    "POST" => handle_post(reader.get_mut(), store, &req, alive),
+                    _ => unreachable!("parse_req rejects unsupported methods"),
                 }
-            } else {
-                format!("Path requested: {}", req.path)
-            };
-            let response = format!(
-                "HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nContent-Length: {}\r\n\r\n{}",
-                body.len(),
-                body
-            );
-            let _ = stream.write_all(response.as_bytes());
-        }
-        Err(e) => {
-            eprintln!("{e}");
-            let body = format!("{e}");
 
-            if let scratch_server::ReqParseError::IoError(_) = e {
-                let response = format!(
-                    "HTTP/1.1 500 Internal Server Error\r\nContent-Type: text/plain\r\nContent-Length: {}\r\n\r\n{}",
-                    body.len(),
-                    body
-                );
-                let _ = stream.write_all(response.as_bytes());
-            } else {
-                let response = format!(
-                    "HTTP/1.1 400 Bad Request\r\nContent-Type: text/plain\r\nContent-Length: {}\r\n\r\n{}",
-                    body.len(),
-                    body
-                );
-                let _ = stream.write_all(response.as_bytes());
+                if !alive {
+                    break;
+                }
+            }
+            Err(ReqParseError::ConnectionClosed) if !first_request => {
+                break;
+            }
+            Err(ReqParseError::ExpectationFailed) => {
+                break;
+            }
+            Err(e) => {
+                eprintln!("{e}");
+                handle_err(reader.get_mut(), &e, false);
+                break;
             }
         }
+
+        first_request = false;
     }
 }